@@ -41,11 +41,12 @@ use tracing::Instrument;
 use turbo_rcstr::RcStr;
 use turbo_tasks::{
     fxindexmap, fxindexset, trace::TraceRawVcs, Completion, FxIndexMap, FxIndexSet, ResolvedVc,
-    TryJoinIterExt, Value, ValueToString, Vc,
+    TryFlatJoinIterExt, TryJoinIterExt, Value, ValueToString, Vc,
 };
 use turbo_tasks_env::{CustomProcessEnv, ProcessEnv};
 use turbo_tasks_fs::{File, FileContent, FileSystemPath};
 use turbopack::{
+    css::CssModuleAsset,
     module_options::{transition_rule::TransitionRule, ModuleOptionsContext, RuleCondition},
     resolve_options_context::ResolveOptionsContext,
     transition::{ContextTransition, FullContextTransition, Transition, TransitionOptions},
@@ -97,6 +98,9 @@ pub struct OptionAppProject(Option<ResolvedVc<AppProject>>);
 
 impl AppProject {}
 
+#[turbo_tasks::value(transparent)]
+pub struct CssClientReferences(Vec<ResolvedVc<CssModuleAsset>>);
+
 pub(crate) const ECMASCRIPT_CLIENT_TRANSITION_NAME: &str = "next-ecmascript-client-reference";
 
 fn styles_rule_condition() -> RuleCondition {
@@ -644,6 +648,30 @@ impl AppProject {
         ))
     }
 
+    /// Collects the CSS client references of all app pages, listing each
+    /// [CssModuleAsset] once in the order it was first seen, e.g. for a
+    /// project-wide stylesheet manifest.
+    #[turbo_tasks::function]
+    pub async fn css_client_references(self: Vc<Self>) -> Result<Vc<CssClientReferences>> {
+        let routes = self.routes().await?;
+        let client_references = css_client_reference_endpoints(routes.values())
+            .into_iter()
+            .map(|endpoint| async move {
+                Ok(
+                    match Vc::try_resolve_downcast_type::<AppEndpoint>(endpoint).await? {
+                        Some(endpoint) => Some(endpoint.client_references().await?.clone_value()),
+                        None => None,
+                    },
+                )
+            })
+            .try_flat_join()
+            .await?;
+
+        Ok(Vc::cell(
+            ClientReferenceGraphResult::unique_css_client_references(&client_references),
+        ))
+    }
+
     #[turbo_tasks::function]
     pub async fn client_main_module(self: Vc<Self>) -> Result<Vc<Box<dyn Module>>> {
         let client_module_context = Vc::upcast(self.client_module_context());
@@ -669,6 +697,25 @@ impl AppProject {
     }
 }
 
+/// The endpoints whose client references are collected by
+/// [AppProject::css_client_references]: the HTML endpoint of each page. The RSC
+/// endpoint of a page has the same client references, and route handlers and
+/// metadata routes don't emit client assets, so they aren't traversed.
+fn css_client_reference_endpoints<'a>(
+    routes: impl IntoIterator<Item = &'a Route>,
+) -> Vec<Vc<Box<dyn Endpoint>>> {
+    routes
+        .into_iter()
+        .flat_map(|route| match route {
+            Route::AppPage(pages) => pages.iter().map(|page| page.html_endpoint).collect(),
+            Route::AppRoute { .. }
+            | Route::Page { .. }
+            | Route::PageApi { .. }
+            | Route::Conflict => vec![],
+        })
+        .collect()
+}
+
 #[turbo_tasks::function]
 pub fn app_entry_point_to_route(
     app_project: ResolvedVc<AppProject>,
@@ -863,6 +910,38 @@ impl AppEndpoint {
         self.output().output_assets()
     }
 
+    /// Finds the client references of the endpoint, layout segment by layout
+    /// segment.
+    #[turbo_tasks::function]
+    async fn client_references(self: Vc<Self>) -> Result<Vc<ClientReferenceGraphResult>> {
+        let rsc_entry = self.app_endpoint_entry().await?.rsc_entry;
+
+        let ServerEntries {
+            server_component_entries,
+            server_utils,
+        } = &*find_server_entries(*rsc_entry).await?;
+
+        let mut client_references = client_reference_graph(
+            server_utils.iter().map(|&v| *v).collect(),
+            VisitedClientReferenceGraphNodes::empty(),
+        )
+        .await?
+        .clone_value();
+
+        for module in server_component_entries
+            .iter()
+            .map(|m| ResolvedVc::upcast::<Box<dyn Module>>(*m))
+            .chain(std::iter::once(rsc_entry))
+        {
+            let current_client_references =
+                client_reference_graph(vec![*module], *client_references.visited_nodes).await?;
+
+            client_references.extend(&current_client_references);
+        }
+
+        Ok(client_references.cell())
+    }
+
     #[turbo_tasks::function]
     async fn output(self: Vc<Self>) -> Result<Vc<AppEndpointOutput>> {
         let this = self.await?;
@@ -894,8 +973,6 @@ impl AppEndpoint {
 
         let runtime = app_entry.config.await?.runtime.unwrap_or_default();
 
-        let rsc_entry = app_entry.rsc_entry;
-
         let client_chunking_context = this.app_project.project().client_chunking_context();
 
         let ssr_chunking_context = if process_ssr {
@@ -933,33 +1010,8 @@ impl AppEndpoint {
                 }
                 let client_shared_availability_info = client_shared_chunk_group.availability_info;
 
-                let client_references = {
-                    let ServerEntries {
-                        server_component_entries,
-                        server_utils,
-                    } = &*find_server_entries(*rsc_entry).await?;
-
-                    let mut client_references = client_reference_graph(
-                        server_utils.iter().map(|&v| *v).collect(),
-                        VisitedClientReferenceGraphNodes::empty(),
-                    )
-                    .await?
-                    .clone_value();
-
-                    for module in server_component_entries
-                        .iter()
-                        .map(|m| ResolvedVc::upcast::<Box<dyn Module>>(*m))
-                        .chain(std::iter::once(rsc_entry))
-                    {
-                        let current_client_references =
-                            client_reference_graph(vec![*module], *client_references.visited_nodes)
-                                .await?;
-
-                        client_references.extend(&current_client_references);
-                    }
-                    client_references
-                };
-                let client_references_cell = client_references.clone().cell();
+                let client_references_cell = self.client_references();
+                let client_references = client_references_cell.await?;
 
                 let client_dynamic_imports = {
                     let mut client_dynamic_imports = FxIndexMap::default();
@@ -1761,3 +1813,45 @@ impl AppEndpointOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use turbo_tasks::{RawVc, TaskId};
+
+    use super::*;
+
+    fn endpoint(id: u32) -> Vc<Box<dyn Endpoint>> {
+        Vc::from(RawVc::TaskOutput(TaskId::from(id)))
+    }
+
+    fn page(name: &str, html_endpoint: u32, rsc_endpoint: u32) -> AppPageRoute {
+        AppPageRoute {
+            original_name: name.to_string(),
+            html_endpoint: endpoint(html_endpoint),
+            rsc_endpoint: endpoint(rsc_endpoint),
+        }
+    }
+
+    #[test]
+    fn test_css_client_reference_endpoints() {
+        // Two pages (e.g. both importing `globals.css`), a metadata route and a route handler
+        let routes = [
+            Route::AppPage(vec![page("/dashboard", 1, 2)]),
+            Route::AppRoute {
+                original_name: "/favicon.ico".to_string(),
+                endpoint: endpoint(3),
+            },
+            Route::AppPage(vec![page("/settings", 4, 5)]),
+            Route::AppRoute {
+                original_name: "/api/hello".to_string(),
+                endpoint: endpoint(6),
+            },
+        ];
+
+        // Only the HTML endpoint of each page is traversed
+        assert_eq!(
+            css_client_reference_endpoints(&routes),
+            [endpoint(1), endpoint(4)]
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
 use anyhow::{bail, Result};
 use futures::Future;
@@ -109,6 +112,32 @@ pub struct NextDynamicImportsResult {
     pub visited_modules: ResolvedVc<VisitedDynamicImportModules>,
 }
 
+#[turbo_tasks::value_impl]
+impl NextDynamicImportsResult {
+    /// Counts the `next/dynamic` imports of each importing module, e.g. for
+    /// budgeting. Modules without dynamic imports are not listed.
+    #[turbo_tasks::function]
+    pub fn dynamic_import_counts(&self) -> Vc<DynamicImportCounts> {
+        Vc::cell(count_per_importer(&self.client_dynamic_imports))
+    }
+}
+
+/// Counts the imports of each importer, omitting the importers without any.
+fn count_per_importer<K: Copy + Eq + Hash, V>(
+    imports: &FxIndexMap<K, Vec<V>>,
+) -> FxIndexMap<K, usize> {
+    imports
+        .iter()
+        .filter(|(_, imports)| !imports.is_empty())
+        .map(|(importer, imports)| (*importer, imports.len()))
+        .collect()
+}
+
+/// The number of `next/dynamic` imports of each importing module, see
+/// [NextDynamicImportsResult::dynamic_import_counts].
+#[turbo_tasks::value(transparent)]
+pub struct DynamicImportCounts(FxIndexMap<ResolvedVc<Box<dyn Module>>, usize>);
+
 #[turbo_tasks::value(shared)]
 pub struct VisitedDynamicImportModules(HashSet<NextDynamicVisitEntry>);
 
@@ -439,3 +468,19 @@ pub struct OptionDynamicImportsMap(Option<ResolvedVc<DynamicImportsMap>>);
 pub struct DynamicImportedChunks(
     pub FxIndexMap<ResolvedVc<Box<dyn Module>>, DynamicImportedOutputAssets>,
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_per_importer() {
+        // A page with two `dynamic()` calls, and a layout without any
+        let imports =
+            FxIndexMap::from_iter([("page", vec!["./chart", "./map"]), ("layout", vec![])]);
+
+        let counts = count_per_importer(&imports);
+
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), [("page", 2)]);
+    }
+}
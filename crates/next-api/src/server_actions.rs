@@ -68,7 +68,7 @@ pub(crate) async fn create_server_actions_manifest(
     asset_context: Vc<Box<dyn AssetContext>>,
     chunking_context: Vc<Box<dyn ChunkingContext>>,
 ) -> Result<Vc<ServerActionsManifest>> {
-    let actions = find_actions(rsc_entry, asset_context);
+    let actions = find_actions(rsc_entry, asset_context, None);
 
     let loader =
         build_server_actions_loader(project_path, page_name.clone(), actions, asset_context);
@@ -190,10 +190,15 @@ async fn build_manifest(
 /// Traverses the entire module graph starting from [Module], looking for magic
 /// comment which identifies server actions. Every found server action will be
 /// returned along with the module which exports that action.
+///
+/// If `layer_filter` is set, only the actions found in that layer are
+/// returned. The modules of the other layer are neither parsed nor brought into
+/// the RSC context.
 #[turbo_tasks::function]
 async fn find_actions(
     rsc_entry: ResolvedVc<Box<dyn Module>>,
     asset_context: Vc<Box<dyn AssetContext>>,
+    layer_filter: Option<ActionLayer>,
 ) -> Result<Vc<AllActions>> {
     async move {
         let actions = NonDeterministic::new()
@@ -210,6 +215,7 @@ async fn find_actions(
             .completed()?
             .into_inner()
             .into_iter()
+            .filter(|(layer, _, _)| includes_layer(layer_filter, *layer))
             .map(parse_actions_filter_map)
             .try_flat_join()
             .await?;
@@ -246,6 +252,15 @@ async fn find_actions(
     .await
 }
 
+/// Whether the actions found in `layer` are returned when filtering by
+/// `layer_filter`, see [find_actions].
+fn includes_layer(layer_filter: Option<ActionLayer>, layer: ActionLayer) -> bool {
+    match layer_filter {
+        Some(layer_filter) => layer_filter == layer,
+        None => true,
+    }
+}
+
 type FindActionsNode = (ActionLayer, ResolvedVc<Box<dyn Module>>, ReadRef<RcStr>);
 struct FindActionsVisit {}
 impl turbo_tasks::graph::Visit<FindActionsNode> for FindActionsVisit {
@@ -523,3 +538,22 @@ impl OptionActionMap {
         Vc::cell(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_layer() {
+        assert!(includes_layer(None, ActionLayer::Rsc));
+        assert!(includes_layer(None, ActionLayer::ActionBrowser));
+
+        // RSC only: the ActionBrowser layer's modules are skipped before being
+        // brought into the RSC context
+        assert!(includes_layer(Some(ActionLayer::Rsc), ActionLayer::Rsc));
+        assert!(!includes_layer(
+            Some(ActionLayer::Rsc),
+            ActionLayer::ActionBrowser
+        ));
+    }
+}
@@ -6,6 +6,8 @@ pub use ecmascript_client_reference::{
     ecmascript_client_reference_transition::NextEcmascriptClientReferenceTransition,
 };
 pub use visit_client_reference::{
-    client_reference_graph, find_server_entries, ClientReference, ClientReferenceGraphResult,
-    ClientReferenceType, ClientReferenceTypes, ServerEntries, VisitedClientReferenceGraphNodes,
+    client_reference_graph, client_reference_graph_of_kind, client_reference_paths,
+    find_server_entries, server_components_in_visit_order, BoundaryKind, ClientReference,
+    ClientReferenceGraphResult, ClientReferenceKind, ClientReferenceType, ClientReferenceTypes,
+    ServerEntries, VisitedClientReferenceGraphNodes, NEXT_INTERNAL_CLIENT_REFERENCE_PATHS,
 };
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, future::Future};
+use std::{collections::HashSet, future::Future, hash::Hash};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -8,7 +8,8 @@ use turbo_tasks::{
     debug::ValueDebugFormat,
     graph::{AdjacencyMap, GraphTraversal, Visit, VisitControlFlow, VisitedNodes},
     trace::TraceRawVcs,
-    FxIndexMap, FxIndexSet, ReadRef, ResolvedVc, TryJoinIterExt, ValueToString, Vc,
+    FxIndexMap, FxIndexSet, ReadRef, ResolvedVc, TaskInput, TryFlatJoinIterExt, TryJoinIterExt,
+    ValueToString, Vc,
 };
 use turbo_tasks_fs::FileSystemPath;
 use turbopack::css::CssModuleAsset;
@@ -49,6 +50,86 @@ pub enum ClientReferenceType {
     CssClientReference(ResolvedVc<CssModuleAsset>),
 }
 
+impl ClientReferenceType {
+    /// The module this client reference points to.
+    pub fn module(&self) -> ResolvedVc<Box<dyn Module>> {
+        match self {
+            ClientReferenceType::EcmascriptClientReference { module, .. } => {
+                ResolvedVc::upcast(*module)
+            }
+            ClientReferenceType::CssClientReference(module) => ResolvedVc::upcast(*module),
+        }
+    }
+
+    pub fn kind(&self) -> ClientReferenceKind {
+        match self {
+            ClientReferenceType::EcmascriptClientReference { .. } => {
+                ClientReferenceKind::Ecmascript
+            }
+            ClientReferenceType::CssClientReference(_) => ClientReferenceKind::Css,
+        }
+    }
+}
+
+/// The kind of a [`ClientReferenceType`], used to only collect one kind of client reference with
+/// [`client_reference_graph_of_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, TaskInput, Serialize, Deserialize)]
+pub enum ClientReferenceKind {
+    Ecmascript,
+    Css,
+}
+
+/// Whether client references of `kind` are collected when filtering by `filter`.
+fn includes_kind(filter: Option<ClientReferenceKind>, kind: ClientReferenceKind) -> bool {
+    match filter {
+        Some(filter) => filter == kind,
+        None => true,
+    }
+}
+
+/// Path prefixes of the framework-internal client references (such as the
+/// `next/dist/esm/client/components/*` modules), to be used with
+/// [`ClientReferenceGraphResult::client_references_excluding_paths`].
+pub const NEXT_INTERNAL_CLIENT_REFERENCE_PATHS: &[&str] = &["next/dist/"];
+
+/// Whether `path` starts with one of `prefixes`, either at its beginning or at any path segment
+/// (to cover packages resolved to `node_modules/...`). `next/dist/` matches
+/// `node_modules/next/dist/...`, but not `node_modules/i18next/dist/...`.
+fn path_has_prefix_segment(path: &str, prefixes: &[&str]) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| path.starts_with(prefix) || path.contains(&format!("/{prefix}")))
+}
+
+async fn client_reference_path_has_prefix(
+    client_reference: &ClientReference,
+    prefixes: &[&str],
+) -> Result<bool> {
+    let path = client_reference.ty.module().ident().path().await?;
+    Ok(path_has_prefix_segment(&path.path, prefixes))
+}
+
+/// The kind of layer boundary a module represents, see
+/// [`ClientReferenceGraphResult::layer_boundaries`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BoundaryKind {
+    /// An ecmascript client reference (`"use client"`), entering the client layer.
+    ClientEntry,
+    /// A server component, entering the server component layer.
+    ServerComponent,
+    /// A CSS client reference.
+    Css,
+}
+
+impl From<ClientReferenceKind> for BoundaryKind {
+    fn from(kind: ClientReferenceKind) -> Self {
+        match kind {
+            ClientReferenceKind::Ecmascript => BoundaryKind::ClientEntry,
+            ClientReferenceKind::Css => BoundaryKind::Css,
+        }
+    }
+}
+
 #[turbo_tasks::value(shared)]
 #[derive(Clone, Debug)]
 pub struct ClientReferenceGraphResult {
@@ -118,6 +199,297 @@ impl ClientReferenceGraphResult {
         // This is merged already by `client_reference_graph` itself
         self.visited_nodes = other.visited_nodes;
     }
+
+    /// Removes repeated modules from each `client_references_by_server_component` bucket,
+    /// keeping the first occurrence. Merged results can list the same SSR module more than once
+    /// under a server component when it is reachable via several paths.
+    pub fn dedup_client_references_by_server_component(&mut self) {
+        dedup_buckets(&mut self.client_references_by_server_component);
+    }
+
+    /// Returns the client references that aren't reached through any server component, other
+    /// than the framework-internal ones (see [`NEXT_INTERNAL_CLIENT_REFERENCE_PATHS`]), which are
+    /// expected to be listed that way. These usually indicate a misconfigured entry.
+    pub async fn orphaned_client_references(&self) -> Result<Vec<ClientReference>> {
+        self.client_references
+            .iter()
+            .filter(|client_reference| client_reference.server_component.is_none())
+            .map(|client_reference| async move {
+                let is_internal = client_reference_path_has_prefix(
+                    client_reference,
+                    NEXT_INTERNAL_CLIENT_REFERENCE_PATHS,
+                )
+                .await?;
+                anyhow::Ok((!is_internal).then_some(*client_reference))
+            })
+            .try_flat_join()
+            .await
+    }
+
+    /// Groups the [`ClientReferenceType::EcmascriptClientReference`]s by the proxy module that
+    /// imports them. CSS client references have no parent module and are not listed.
+    pub fn client_references_by_parent_module(
+        &self,
+    ) -> FxIndexMap<ResolvedVc<EcmascriptClientReferenceProxyModule>, Vec<ClientReference>> {
+        group_in_order(
+            self.client_references
+                .iter()
+                .filter_map(|client_reference| match client_reference.ty {
+                    ClientReferenceType::EcmascriptClientReference { parent_module, .. } => {
+                        Some((parent_module, *client_reference))
+                    }
+                    ClientReferenceType::CssClientReference(_) => None,
+                }),
+        )
+    }
+
+    /// Merges multiple return values of client_reference_graph into a new result. Unlike
+    /// [`ClientReferenceGraphResult::extend`], duplicates are dropped (keeping the first
+    /// occurrence) and the `None` bucket of `client_references_by_server_component` is always
+    /// listed first.
+    ///
+    /// Like with [`ClientReferenceGraphResult::extend`], `visited_nodes` is taken from the last
+    /// result. That covers the nodes of all results when they were computed one after the other,
+    /// passing each call the previous result's `visited_nodes`. Otherwise, it only covers the
+    /// last result and shouldn't be used for further traversals.
+    pub fn merge_results(results: &[ClientReferenceGraphResult]) -> ClientReferenceGraphResult {
+        let mut merged = ClientReferenceGraphResult::default();
+        for result in results {
+            merged.extend(result);
+        }
+        dedup_in_order(&mut merged.client_references);
+        dedup_in_order(&mut merged.server_component_entries);
+        dedup_in_order(&mut merged.server_utils);
+        merged.client_references_by_server_component = merge_buckets(
+            results
+                .iter()
+                .map(|result| &result.client_references_by_server_component),
+        );
+        merged
+    }
+
+    /// Returns the client references whose module path doesn't start with any of
+    /// `excluded_prefixes`, at its beginning or at a path segment. Pass
+    /// [`NEXT_INTERNAL_CLIENT_REFERENCE_PATHS`] to keep only the references from the user's
+    /// source tree.
+    pub async fn client_references_excluding_paths(
+        &self,
+        excluded_prefixes: &[&str],
+    ) -> Result<Vec<ClientReference>> {
+        let excluded = self
+            .client_references
+            .iter()
+            .map(|client_reference| {
+                client_reference_path_has_prefix(client_reference, excluded_prefixes)
+            })
+            .try_join()
+            .await?;
+        Ok(self
+            .client_references
+            .iter()
+            .zip(excluded)
+            .filter(|(_, excluded)| !excluded)
+            .map(|(client_reference, _)| *client_reference)
+            .collect())
+    }
+
+    /// Groups the [`ClientReferenceType::CssClientReference`]s by server component, like
+    /// `client_references_by_server_component` does for the ecmascript ones. The `None` bucket
+    /// is listed first.
+    #[allow(clippy::type_complexity)]
+    pub fn css_client_references_by_server_component(
+        &self,
+    ) -> FxIndexMap<Option<ResolvedVc<NextServerComponentModule>>, Vec<ResolvedVc<CssModuleAsset>>>
+    {
+        group_by_optional_key(
+            self.client_references
+                .iter()
+                .filter_map(|client_reference| match client_reference.ty {
+                    ClientReferenceType::CssClientReference(css_module) => {
+                        Some((client_reference.server_component, css_module))
+                    }
+                    ClientReferenceType::EcmascriptClientReference { .. } => None,
+                }),
+        )
+    }
+
+    /// Lists every module at a layer boundary together with its [`BoundaryKind`]: the server
+    /// components first, followed by the client references. Each module is listed once.
+    pub fn layer_boundaries(&self) -> Vec<(ResolvedVc<Box<dyn Module>>, BoundaryKind)> {
+        layer_boundaries(
+            self.server_component_entries
+                .iter()
+                .map(|server_component| ResolvedVc::upcast::<Box<dyn Module>>(*server_component)),
+            self.client_references
+                .iter()
+                .map(|client_reference| (client_reference.ty.module(), client_reference.ty.kind())),
+        )
+    }
+
+    /// Collects the CSS client references of multiple results (e.g. one per endpoint), listing
+    /// each [`CssModuleAsset`] once in the order it was first seen. Useful for a project-wide
+    /// stylesheet manifest.
+    pub fn unique_css_client_references(
+        results: &[ClientReferenceGraphResult],
+    ) -> Vec<ResolvedVc<CssModuleAsset>> {
+        unique_in_order(results.iter().map(|result| {
+            result
+                .client_references
+                .iter()
+                .filter_map(|client_reference| match client_reference.ty {
+                    ClientReferenceType::CssClientReference(css_module) => Some(css_module),
+                    ClientReferenceType::EcmascriptClientReference { .. } => None,
+                })
+        }))
+    }
+
+    /// Counts the entries of each `client_references_by_server_component` bucket, keeping the
+    /// bucket order (`None` first). Like that map, this only counts the
+    /// [`ClientReferenceType::EcmascriptClientReference`]s; CSS client references are not
+    /// included.
+    pub fn client_reference_counts_by_server_component(
+        &self,
+    ) -> FxIndexMap<Option<ResolvedVc<NextServerComponentModule>>, usize> {
+        bucket_sizes(&self.client_references_by_server_component)
+    }
+}
+
+/// Walks the graph given by `roots` and `children` depth-first, calling `visit` for each node the
+/// first time it is reached, together with the nodes on the path leading to it from a root.
+/// Children are visited in the order `children` returns them, so the walk is deterministic.
+fn walk_pre_order<T, I>(
+    roots: impl IntoIterator<Item = T>,
+    mut children: impl FnMut(&T) -> I,
+    mut visit: impl FnMut(&T, &[T]),
+) where
+    T: Clone + Eq + Hash,
+    I: IntoIterator<Item = T>,
+{
+    let mut visited = HashSet::new();
+    let mut path = Vec::new();
+    let mut stack: Vec<_> = roots.into_iter().map(|root| (0, root)).collect();
+    stack.reverse();
+    while let Some((depth, node)) = stack.pop() {
+        if !visited.insert(node.clone()) {
+            continue;
+        }
+        path.truncate(depth);
+        visit(&node, &path);
+        let node_children: Vec<_> = children(&node).into_iter().collect();
+        stack.extend(
+            node_children
+                .into_iter()
+                .rev()
+                .map(|child| (depth + 1, child)),
+        );
+        path.push(node);
+    }
+}
+
+fn dedup_in_order<T: Copy + Eq + Hash>(items: &mut Vec<T>) {
+    let mut seen = HashSet::new();
+    items.retain(|item| seen.insert(*item));
+}
+
+fn dedup_buckets<K, T: Copy + Eq + Hash>(buckets: &mut FxIndexMap<K, Vec<T>>) {
+    for items in buckets.values_mut() {
+        dedup_in_order(items);
+    }
+}
+
+/// The initial `client_references_by_server_component` map of a traversal collecting the client
+/// references included by `kind`. The `None` bucket (for the various internal
+/// next/dist/esm/client/components/*) is inserted first so that it's listed first, but only if
+/// ecmascript client references are collected.
+fn empty_server_component_buckets<K: Eq + Hash, T>(
+    kind: Option<ClientReferenceKind>,
+) -> FxIndexMap<Option<K>, Vec<T>> {
+    let mut buckets = FxIndexMap::default();
+    if includes_kind(kind, ClientReferenceKind::Ecmascript) {
+        buckets.insert(None, Vec::new());
+    }
+    buckets
+}
+
+/// Groups the values by their keys, keeping the order in which keys and values are first seen.
+fn group_in_order<K: Eq + Hash, V>(
+    items: impl IntoIterator<Item = (K, V)>,
+) -> FxIndexMap<K, Vec<V>> {
+    let mut groups = FxIndexMap::default();
+    for (key, value) in items {
+        groups.entry(key).or_insert_with(Vec::new).push(value);
+    }
+    groups
+}
+
+/// Merges the buckets of multiple maps, listing the `None` bucket first and each value once per
+/// bucket.
+fn merge_buckets<'a, K, T>(
+    maps: impl IntoIterator<Item = &'a FxIndexMap<Option<K>, Vec<T>>>,
+) -> FxIndexMap<Option<K>, Vec<T>>
+where
+    K: Copy + Eq + Hash + 'a,
+    T: Copy + Eq + Hash + 'a,
+{
+    let mut merged = FxIndexMap::default();
+    merged.insert(None, Vec::new());
+    for map in maps {
+        for (key, values) in map {
+            merged
+                .entry(*key)
+                .or_insert_with(Vec::new)
+                .extend(values.iter().copied());
+        }
+    }
+    dedup_buckets(&mut merged);
+    merged
+}
+
+/// Like [`group_in_order`], but always lists the `None` group first.
+fn group_by_optional_key<K: Eq + Hash, V>(
+    items: impl IntoIterator<Item = (Option<K>, V)>,
+) -> FxIndexMap<Option<K>, Vec<V>> {
+    let mut groups = FxIndexMap::default();
+    groups.insert(None, Vec::new());
+    for (key, value) in items {
+        groups.entry(key).or_insert_with(Vec::new).push(value);
+    }
+    groups
+}
+
+/// Lists the `server_components` followed by the `client_references` with their
+/// [`BoundaryKind`]s, each module once.
+fn layer_boundaries<M: Copy + Eq + Hash>(
+    server_components: impl IntoIterator<Item = M>,
+    client_references: impl IntoIterator<Item = (M, ClientReferenceKind)>,
+) -> Vec<(M, BoundaryKind)> {
+    let mut boundaries: Vec<_> = server_components
+        .into_iter()
+        .map(|module| (module, BoundaryKind::ServerComponent))
+        .chain(
+            client_references
+                .into_iter()
+                .map(|(module, kind)| (module, kind.into())),
+        )
+        .collect();
+    dedup_in_order(&mut boundaries);
+    boundaries
+}
+
+/// Concatenates the lists, keeping only the first occurrence of each item.
+fn unique_in_order<T: Copy + Eq + Hash>(
+    lists: impl IntoIterator<Item = impl IntoIterator<Item = T>>,
+) -> Vec<T> {
+    let mut items: Vec<_> = lists.into_iter().flatten().collect();
+    dedup_in_order(&mut items);
+    items
+}
+
+fn bucket_sizes<K: Copy + Eq + Hash, T>(buckets: &FxIndexMap<K, Vec<T>>) -> FxIndexMap<K, usize> {
+    buckets
+        .iter()
+        .map(|(key, items)| (*key, items.len()))
+        .collect()
 }
 
 #[turbo_tasks::function]
@@ -125,46 +497,47 @@ pub async fn client_reference_graph(
     entries: Vec<ResolvedVc<Box<dyn Module>>>,
     visited_nodes: Vc<VisitedClientReferenceGraphNodes>,
 ) -> Result<Vc<ClientReferenceGraphResult>> {
+    Ok(client_reference_graph_inner(entries, visited_nodes, None)
+        .await?
+        .cell())
+}
+
+/// Like [`client_reference_graph`], but only collects the client references of the given kind
+/// (e.g. only the CSS ones when computing critical CSS). `client_references_by_server_component`
+/// is empty unless ecmascript client references are collected. The returned `visited_nodes`
+/// should only be passed to traversals of the same kind.
+#[turbo_tasks::function]
+pub async fn client_reference_graph_of_kind(
+    entries: Vec<ResolvedVc<Box<dyn Module>>>,
+    visited_nodes: Vc<VisitedClientReferenceGraphNodes>,
+    kind: ClientReferenceKind,
+) -> Result<Vc<ClientReferenceGraphResult>> {
+    Ok(
+        client_reference_graph_inner(entries, visited_nodes, Some(kind))
+            .await?
+            .cell(),
+    )
+}
+
+async fn client_reference_graph_inner(
+    entries: Vec<ResolvedVc<Box<dyn Module>>>,
+    visited_nodes: Vc<VisitedClientReferenceGraphNodes>,
+    kind: Option<ClientReferenceKind>,
+) -> Result<ClientReferenceGraphResult> {
     async move {
         let mut client_references = vec![];
         let mut server_component_entries = vec![];
         let mut server_utils = vec![];
 
-        let mut client_references_by_server_component = FxIndexMap::default();
-        // Make sure None (for the various internal next/dist/esm/client/components/*) is listed
-        // first
-        client_references_by_server_component.insert(None, Vec::new());
+        let mut client_references_by_server_component = empty_server_component_buckets(kind);
 
         let (graph, visited_nodes) = AdjacencyMap::new()
             .skip_duplicates_with_visited_nodes(VisitedNodes(visited_nodes.await?.0.clone()))
             .visit(
-                entries
-                    .iter()
-                    .copied()
-                    .map(|module| async move {
-                        Ok(VisitClientReferenceNode {
-                            state: if let Some(server_component) =
-                                ResolvedVc::try_downcast_type::<NextServerComponentModule>(module)
-                                    .await?
-                            {
-                                VisitClientReferenceNodeState::InServerComponent {
-                                    server_component: *server_component,
-                                }
-                            } else {
-                                VisitClientReferenceNodeState::Entry {
-                                    entry_path: module.ident().path().resolve().await?,
-                                }
-                            },
-                            ty: VisitClientReferenceNodeType::Internal(
-                                module,
-                                module.ident().to_string().await?,
-                            ),
-                        })
-                    })
-                    .try_join()
-                    .await?,
+                client_reference_graph_roots(&entries).await?,
                 VisitClientReference {
                     stop_at_server_entries: false,
+                    kind,
                 },
             )
             .await
@@ -207,13 +580,112 @@ pub async fn client_reference_graph(
             server_component_entries,
             server_utils,
             visited_nodes: VisitedClientReferenceGraphNodes(visited_nodes.0).resolved_cell(),
-        }
-        .cell())
+        })
     }
     .instrument(tracing::info_span!("find client references"))
     .await
 }
 
+/// Lists the server components reachable from `entries` in the order they are first visited, so
+/// that a server component is listed before the ones nested in it. Unlike
+/// [`ClientReferenceGraphResult::server_component_entries`], which lists children before their
+/// parents, this order is suited for manifest generation. This runs its own traversal.
+pub async fn server_components_in_visit_order(
+    entries: Vec<ResolvedVc<Box<dyn Module>>>,
+) -> Result<Vec<ResolvedVc<NextServerComponentModule>>> {
+    let graph = full_client_reference_graph(&entries).await?;
+
+    let mut server_components = vec![];
+    walk_pre_order(
+        graph.roots(),
+        |node| graph.get(node).into_iter().flatten(),
+        |node, _| {
+            if let VisitClientReferenceNodeType::ServerComponentEntry(server_component, _) =
+                &node.ty
+            {
+                server_components.push(*server_component);
+            }
+        },
+    );
+
+    Ok(server_components)
+}
+
+/// Lists each client reference reachable from `entries` together with the path through which it
+/// was first discovered (e.g. to explain why a client component is part of a page).
+///
+/// The path lists the modules from the entry (its first element) down to the module importing
+/// the client reference, which is the last element. The client reference itself is not part of
+/// its path. This runs its own traversal and is meant for diagnostics only.
+pub async fn client_reference_paths(
+    entries: Vec<ResolvedVc<Box<dyn Module>>>,
+) -> Result<Vec<(ClientReference, Vec<ResolvedVc<Box<dyn Module>>>)>> {
+    let graph = full_client_reference_graph(&entries).await?;
+
+    let mut paths = vec![];
+    walk_pre_order(
+        graph.roots(),
+        |node| graph.get(node).into_iter().flatten(),
+        |node, path| {
+            if let VisitClientReferenceNodeType::ClientReference(client_reference, _) = &node.ty {
+                paths.push((
+                    *client_reference,
+                    path.iter().map(|node| node.ty.module()).collect(),
+                ));
+            }
+        },
+    );
+
+    Ok(paths)
+}
+
+/// Traverses the client reference graph of `entries`, independently of any other traversal.
+async fn full_client_reference_graph(
+    entries: &[ResolvedVc<Box<dyn Module>>],
+) -> Result<AdjacencyMap<VisitClientReferenceNode>> {
+    Ok(AdjacencyMap::new()
+        .skip_duplicates()
+        .visit(
+            client_reference_graph_roots(entries).await?,
+            VisitClientReference {
+                stop_at_server_entries: false,
+                kind: None,
+            },
+        )
+        .await
+        .completed()?
+        .into_inner())
+}
+
+async fn client_reference_graph_roots(
+    entries: &[ResolvedVc<Box<dyn Module>>],
+) -> Result<Vec<VisitClientReferenceNode>> {
+    entries
+        .iter()
+        .copied()
+        .map(|module| async move {
+            Ok(VisitClientReferenceNode {
+                state: if let Some(server_component) =
+                    ResolvedVc::try_downcast_type::<NextServerComponentModule>(module).await?
+                {
+                    VisitClientReferenceNodeState::InServerComponent {
+                        server_component: *server_component,
+                    }
+                } else {
+                    VisitClientReferenceNodeState::Entry {
+                        entry_path: module.ident().path().resolve().await?,
+                    }
+                },
+                ty: VisitClientReferenceNodeType::Internal(
+                    module,
+                    module.ident().to_string().await?,
+                ),
+            })
+        })
+        .try_join()
+        .await
+}
+
 #[turbo_tasks::value(shared)]
 #[derive(Clone, Debug)]
 pub struct ServerEntries {
@@ -236,6 +708,7 @@ pub async fn find_server_entries(entry: ResolvedVc<Box<dyn Module>>) -> Result<V
             }],
             VisitClientReference {
                 stop_at_server_entries: true,
+                kind: None,
             },
         )
         .await
@@ -267,6 +740,8 @@ pub async fn find_server_entries(entry: ResolvedVc<Box<dyn Module>>) -> Result<V
 struct VisitClientReference {
     /// Used to discover ServerComponents and ServerUtils
     stop_at_server_entries: bool,
+    /// Only client references of this kind are collected, if set
+    kind: Option<ClientReferenceKind>,
 }
 
 #[derive(
@@ -311,6 +786,21 @@ enum VisitClientReferenceNodeType {
     Internal(ResolvedVc<Box<dyn Module>>, ReadRef<RcStr>),
 }
 
+impl VisitClientReferenceNodeType {
+    fn module(&self) -> ResolvedVc<Box<dyn Module>> {
+        match self {
+            VisitClientReferenceNodeType::ClientReference(client_reference, _) => {
+                client_reference.ty.module()
+            }
+            VisitClientReferenceNodeType::ServerComponentEntry(module, _) => {
+                ResolvedVc::upcast(*module)
+            }
+            VisitClientReferenceNodeType::ServerUtilEntry(module, _)
+            | VisitClientReferenceNodeType::Internal(module, _) => *module,
+        }
+    }
+}
+
 impl Visit<VisitClientReferenceNode> for VisitClientReference {
     type Edge = VisitClientReferenceNode;
     type EdgesIntoIter = Vec<Self::Edge>;
@@ -339,6 +829,7 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
 
     fn edges(&mut self, node: &VisitClientReferenceNode) -> Self::EdgesFuture {
         let node = node.clone();
+        let kind = self.kind;
         async move {
             let parent_module = match node.ty {
                 // This should never occur since we always skip visiting these
@@ -358,7 +849,12 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
                 if let Some(client_reference_module) =
                     ResolvedVc::try_downcast_type::<EcmascriptClientReferenceModule>(module).await?
                 {
-                    return Ok(VisitClientReferenceNode {
+                    // Client references of other kinds are dropped before building their node,
+                    // they must not be traversed as internal modules either.
+                    if !includes_kind(kind, ClientReferenceKind::Ecmascript) {
+                        return Ok(None);
+                    }
+                    return Ok(Some(VisitClientReferenceNode {
                         state: node.state,
                         ty: VisitClientReferenceNodeType::ClientReference(
                             ClientReference {
@@ -381,13 +877,16 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
                             },
                             client_reference_module.ident().to_string().await?,
                         ),
-                    });
+                    }));
                 }
 
                 if let Some(css_client_reference_asset) =
                     ResolvedVc::try_downcast_type::<CssModuleAsset>(module).await?
                 {
-                    return Ok(VisitClientReferenceNode {
+                    if !includes_kind(kind, ClientReferenceKind::Css) {
+                        return Ok(None);
+                    }
+                    return Ok(Some(VisitClientReferenceNode {
                         state: node.state,
                         ty: VisitClientReferenceNodeType::ClientReference(
                             ClientReference {
@@ -403,13 +902,13 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
                             },
                             css_client_reference_asset.ident().to_string().await?,
                         ),
-                    });
+                    }));
                 }
 
                 if let Some(server_component_asset) =
                     ResolvedVc::try_downcast_type::<NextServerComponentModule>(module).await?
                 {
-                    return Ok(VisitClientReferenceNode {
+                    return Ok(Some(VisitClientReferenceNode {
                         state: VisitClientReferenceNodeState::InServerComponent {
                             server_component: *server_component_asset,
                         },
@@ -417,31 +916,31 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
                             server_component_asset,
                             server_component_asset.ident().to_string().await?,
                         ),
-                    });
+                    }));
                 }
 
                 if let VisitClientReferenceNodeState::Entry { entry_path } = &node.state {
                     if module.ident().path().resolve().await? != *entry_path {
-                        return Ok(VisitClientReferenceNode {
+                        return Ok(Some(VisitClientReferenceNode {
                             state: VisitClientReferenceNodeState::InServerUtil,
                             ty: VisitClientReferenceNodeType::ServerUtilEntry(
                                 module,
                                 module.ident().to_string().await?,
                             ),
-                        });
+                        }));
                     }
                 }
 
-                Ok(VisitClientReferenceNode {
+                Ok(Some(VisitClientReferenceNode {
                     state: node.state,
                     ty: VisitClientReferenceNodeType::Internal(
                         module,
                         module.ident().to_string().await?,
                     ),
-                })
+                }))
             });
 
-            let assets = referenced_modules.try_join().await?;
+            let assets = referenced_modules.try_flat_join().await?;
 
             Ok(assets)
         }
@@ -464,3 +963,285 @@ impl Visit<VisitClientReferenceNode> for VisitClientReference {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_pre_order_lists_parents_first() {
+        // layout -> page -> nested, layout -> sibling -> nested
+        let children = FxIndexMap::from_iter([
+            ("layout", vec!["page", "sibling"]),
+            ("page", vec!["nested"]),
+            ("sibling", vec!["nested"]),
+        ]);
+
+        let mut order = vec![];
+        walk_pre_order(
+            ["layout"],
+            |node| children.get(node).into_iter().flatten().copied(),
+            |node, _| order.push(*node),
+        );
+
+        assert_eq!(order, ["layout", "page", "nested", "sibling"]);
+    }
+
+    #[test]
+    fn test_dedup_in_order() {
+        let mut items = vec![3, 1, 3, 2, 1];
+        dedup_in_order(&mut items);
+        assert_eq!(items, [3, 1, 2]);
+    }
+
+    #[test]
+    fn test_dedup_buckets() {
+        // The shared client component is imported by two children of the `layout` server
+        // component, so its SSR module is listed twice in that bucket.
+        let mut buckets = FxIndexMap::from_iter([
+            (None, vec!["layout-router"]),
+            (Some("layout"), vec!["shared", "header", "shared", "footer"]),
+            (Some("page"), vec!["shared"]),
+        ]);
+
+        dedup_buckets(&mut buckets);
+
+        assert_eq!(buckets[&None], ["layout-router"]);
+        assert_eq!(buckets[&Some("layout")], ["shared", "header", "footer"]);
+        assert_eq!(buckets[&Some("page")], ["shared"]);
+    }
+
+    #[test]
+    fn test_walk_pre_order_paths() {
+        // page -> layout-router -> client-component, page -> client-component
+        let children = FxIndexMap::from_iter([
+            ("page", vec!["layout-router", "client-component"]),
+            ("layout-router", vec!["client-component"]),
+        ]);
+
+        let mut paths = vec![];
+        walk_pre_order(
+            ["page"],
+            |node| children.get(node).into_iter().flatten().copied(),
+            |node, path| paths.push((*node, path.to_vec())),
+        );
+
+        // The path of the client component starts at the entry and ends at its importer, without
+        // the client component itself
+        let (_, client_component_path) = paths
+            .iter()
+            .find(|(node, _)| *node == "client-component")
+            .unwrap();
+        assert_eq!(client_component_path.first(), Some(&"page"));
+        assert!(!client_component_path.contains(&"client-component"));
+
+        assert_eq!(
+            paths,
+            [
+                ("page", vec![]),
+                ("layout-router", vec!["page"]),
+                ("client-component", vec!["page", "layout-router"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_includes_kind() {
+        assert!(includes_kind(None, ClientReferenceKind::Ecmascript));
+        assert!(includes_kind(None, ClientReferenceKind::Css));
+
+        // CSS only
+        assert!(includes_kind(
+            Some(ClientReferenceKind::Css),
+            ClientReferenceKind::Css
+        ));
+        assert!(!includes_kind(
+            Some(ClientReferenceKind::Css),
+            ClientReferenceKind::Ecmascript
+        ));
+    }
+
+    #[test]
+    fn test_css_only_server_component_buckets() {
+        // Only collecting CSS client references, there are no ecmascript ones to list per server
+        // component, not even the internal ones
+        let css_only: FxIndexMap<Option<&str>, Vec<&str>> =
+            empty_server_component_buckets(Some(ClientReferenceKind::Css));
+        assert!(css_only.is_empty());
+
+        let all: FxIndexMap<Option<&str>, Vec<&str>> = empty_server_component_buckets(None);
+        assert_eq!(all.keys().copied().collect::<Vec<_>>(), [None]);
+    }
+
+    #[test]
+    fn test_internal_client_reference_paths() {
+        let internal = NEXT_INTERNAL_CLIENT_REFERENCE_PATHS;
+
+        // A user client component, e.g. imported directly by the root
+        assert!(!path_has_prefix_segment(
+            "app/client-component.tsx",
+            internal
+        ));
+
+        // The internal client components
+        assert!(path_has_prefix_segment(
+            "node_modules/next/dist/esm/client/components/layout-router.js",
+            internal
+        ));
+        assert!(path_has_prefix_segment(
+            "next/dist/esm/client/components/layout-router.js",
+            internal
+        ));
+    }
+
+    #[test]
+    fn test_group_in_order_by_parent_module() {
+        // Two proxy modules, each importing its own client components
+        let grouped = group_in_order([
+            ("header-proxy", "header"),
+            ("footer-proxy", "footer"),
+            ("header-proxy", "nav"),
+            ("footer-proxy", "copyright"),
+        ]);
+
+        assert_eq!(
+            grouped.keys().copied().collect::<Vec<_>>(),
+            ["header-proxy", "footer-proxy"]
+        );
+        assert_eq!(grouped["header-proxy"], ["header", "nav"]);
+        assert_eq!(grouped["footer-proxy"], ["footer", "copyright"]);
+    }
+
+    #[test]
+    fn test_merge_buckets() {
+        let first = FxIndexMap::from_iter([(Some("layout"), vec!["header", "nav"])]);
+        let second = FxIndexMap::from_iter([
+            (None, vec!["layout-router"]),
+            (Some("layout"), vec!["nav", "footer"]),
+        ]);
+        let third = FxIndexMap::from_iter([
+            (Some("page"), vec!["form"]),
+            (None, vec!["layout-router", "error-boundary"]),
+        ]);
+
+        let merged = merge_buckets([&first, &second, &third]);
+
+        assert_eq!(
+            merged.keys().copied().collect::<Vec<_>>(),
+            [None, Some("layout"), Some("page")]
+        );
+        assert_eq!(merged[&None], ["layout-router", "error-boundary"]);
+        assert_eq!(merged[&Some("layout")], ["header", "nav", "footer"]);
+        assert_eq!(merged[&Some("page")], ["form"]);
+    }
+
+    #[test]
+    fn test_user_client_reference_paths() {
+        let paths = [
+            "app/client-component.tsx",
+            "node_modules/next/dist/esm/client/components/layout-router.js",
+            "node_modules/i18next/dist/esm/i18next.js",
+            "next/dist/esm/client/components/error-boundary.js",
+            "node_modules/react-i18next/dist/es/index.js",
+        ];
+
+        let user_paths: Vec<_> = paths
+            .into_iter()
+            .filter(|path| !path_has_prefix_segment(path, NEXT_INTERNAL_CLIENT_REFERENCE_PATHS))
+            .collect();
+
+        // Packages whose name ends with `next` are not framework internals
+        assert_eq!(
+            user_paths,
+            [
+                "app/client-component.tsx",
+                "node_modules/i18next/dist/esm/i18next.js",
+                "node_modules/react-i18next/dist/es/index.js",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_optional_key() {
+        // CSS references of the layout, of a page nested in it, and one outside of any server
+        // component
+        let grouped = group_by_optional_key([
+            (Some("layout"), "globals.css"),
+            (Some("page"), "page.module.css"),
+            (None, "font.css"),
+            (Some("layout"), "layout.module.css"),
+        ]);
+
+        assert_eq!(
+            grouped.keys().copied().collect::<Vec<_>>(),
+            [None, Some("layout"), Some("page")]
+        );
+        assert_eq!(grouped[&None], ["font.css"]);
+        assert_eq!(
+            grouped[&Some("layout")],
+            ["globals.css", "layout.module.css"]
+        );
+        assert_eq!(grouped[&Some("page")], ["page.module.css"]);
+    }
+
+    #[test]
+    fn test_layer_boundaries() {
+        let boundaries = layer_boundaries(
+            ["layout", "page"],
+            [
+                ("layout-router", ClientReferenceKind::Ecmascript),
+                ("globals.css", ClientReferenceKind::Css),
+                ("counter", ClientReferenceKind::Ecmascript),
+                // Imported by both the layout and the page
+                ("counter", ClientReferenceKind::Ecmascript),
+            ],
+        );
+
+        assert_eq!(
+            boundaries,
+            [
+                ("layout", BoundaryKind::ServerComponent),
+                ("page", BoundaryKind::ServerComponent),
+                ("layout-router", BoundaryKind::ClientEntry),
+                ("globals.css", BoundaryKind::Css),
+                ("counter", BoundaryKind::ClientEntry),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unique_in_order_across_endpoints() {
+        // Two endpoints importing the same global CSS file
+        let first_endpoint = ["globals.css", "dashboard.module.css"];
+        let second_endpoint = ["globals.css", "settings.module.css"];
+
+        assert_eq!(
+            unique_in_order([first_endpoint, second_endpoint]),
+            ["globals.css", "dashboard.module.css", "settings.module.css"]
+        );
+    }
+
+    #[test]
+    fn test_bucket_sizes() {
+        // A layout with a nested page, and the internal client components outside of any
+        // server component
+        let buckets = FxIndexMap::from_iter([
+            (None, vec!["layout-router", "error-boundary"]),
+            (Some("layout"), vec!["header", "nav", "footer"]),
+            (Some("page"), vec!["form"]),
+            (Some("empty-page"), vec![]),
+        ]);
+
+        let sizes = bucket_sizes(&buckets);
+
+        assert_eq!(
+            sizes.into_iter().collect::<Vec<_>>(),
+            [
+                (None, 2),
+                (Some("layout"), 3),
+                (Some("page"), 1),
+                (Some("empty-page"), 0),
+            ]
+        );
+    }
+}